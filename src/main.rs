@@ -5,56 +5,262 @@
     coordinates on the Cartesian plane. We want to color the point black
     if â€œc is in the Mandelbrot set, or a lighter color otherwise. So for each
     pixel in our image, we must run the preceding loop on the corresponding point
-    on the complex plane. 
+    on the complex plane.
 */
 
 use::num::Complex;
 use::std::str::FromStr;
 
+use::std::fs::File;
+use::image::png::PNGEncoder;
+use::image::ColorType;
+use::rand::Rng;
+
+/// The family of escape-time fractals we know how to draw.
+///
+/// Each variant only differs in the per-iteration step applied to `z`; the
+/// radius-two bailout is shared by all of them.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum FractalKind {
+    /// The classic `z = z*z + c`.
+    Mandelbrot,
+    /// `z = z*z*z + c`.
+    MandelbrotCubed,
+    /// Take the absolute value of each component before `z = z*z + c`.
+    BurningShip,
+}
+
+impl FromStr for FractalKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mandelbrot" => Ok(FractalKind::Mandelbrot),
+            "mandelbrot_cubed" => Ok(FractalKind::MandelbrotCubed),
+            "burning_ship" => Ok(FractalKind::BurningShip),
+            other => Err(format!("unknown fractal: {}", other))
+        }
+    }
+}
+
+impl FractalKind {
+    /// Apply a single iteration of this fractal's recurrence to `z`.
+    fn step(self, z: Complex<f64>, c: Complex<f64>) -> Complex<f64> {
+        match self {
+            FractalKind::Mandelbrot => z * z + c,
+            FractalKind::MandelbrotCubed => z * z * z + c,
+            FractalKind::BurningShip => {
+                let z = Complex { re: z.re.abs(), im: z.im.abs() };
+                z * z + c
+            }
+        }
+    }
+}
+
 /// ### Returns
 /// `Some(i)` on failure, otherwise `None`
-/// 
+///
 /// ### Overview
-/// Try to determine if `c` is in the Mandelbrot set, using at most `limit`
-/// iterations to decide. 
-/// 
-/// If `c` is not a member, return `Some(i)`, where `i` is the number of 
+/// Try to determine if `c` is in the `kind` fractal set, using at most `limit`
+/// iterations to decide.
+///
+/// If `c` is not a member, return `Some(i)`, where `i` is the number of
 /// iterations it took for `c` to leave the circle of radius two centered
 /// on the origin. If `c` seems to be a member (more precisely, if we reach
 /// the iteration limit without being able to prove that `c` i not a member),
 /// return `None`.
-fn escape_time(c: Complex<f64>, limit: usize) -> Option<usize> {
+fn escape_time(c: Complex<f64>, kind: FractalKind, limit: usize) -> Option<usize> {
     let mut z: Complex<f64> = Complex { re: 0.0, im: 0.0 };
     for i in 0..limit {
         if z.norm_sqr() > 4.0 { // distance from origin
             return Some(i);
         }
-        z = z * z + c;
+        z = kind.step(z, c);
+    }
+    None
+}
+
+/// Like `escape_time`, but returns the *normalized* (continuous) iteration
+/// count so that neighbouring pixels blend smoothly instead of banding.
+///
+/// On escape at iteration `i` we fold the final magnitude back in as a
+/// fractional offset; a point that never escapes returns `None`.
+fn escape_time_smooth(c: Complex<f64>, kind: FractalKind, limit: usize) -> Option<f64> {
+    let mut z: Complex<f64> = Complex { re: 0.0, im: 0.0 };
+    for i in 0..limit {
+        if z.norm_sqr() > 4.0 {
+            return Some(i as f64 + 1.0 - (z.norm_sqr().ln() / 2.0).ln() / 2f64.ln());
+        }
+        z = kind.step(z, c);
     }
     None
 }
 
-/// Parse a pair of floating-point numbers separated by a comma as a complex number.
+/// A color ramp used to turn a normalized escape value in `0.0..=1.0` into an
+/// RGB triple for smooth (continuous) coloring.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Palette {
+    /// Sweep the hue around the color wheel at full saturation and value.
+    Hsv,
+    /// A warm black-red-yellow-white ramp.
+    Fire,
+}
+
+impl FromStr for Palette {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hsv" => Ok(Palette::Hsv),
+            "fire" => Ok(Palette::Fire),
+            other => Err(format!("unknown palette: {}", other))
+        }
+    }
+}
+
+impl Palette {
+    /// Map a normalized value `t` in `0.0..=1.0` to an 8-bit RGB triple.
+    fn color(self, t: f64) -> [u8; 3] {
+        match self {
+            Palette::Hsv => hsv_to_rgb(t * 360.0, 1.0, 1.0),
+            Palette::Fire => {
+                // Piecewise ramp: black -> red -> yellow -> white.
+                let r = (t * 3.0).min(1.0);
+                let g = (t * 3.0 - 1.0).clamp(0.0, 1.0);
+                let b = (t * 3.0 - 2.0).clamp(0.0, 1.0);
+                [(r * 255.0) as u8, (g * 255.0) as u8, (b * 255.0) as u8]
+            }
+        }
+    }
+}
+
+/// Convert an HSV color (hue in degrees, saturation and value in `0.0..=1.0`)
+/// to an 8-bit RGB triple.
+fn hsv_to_rgb(h: f64, s: f64, v: f64) -> [u8; 3] {
+    let c = v * s;
+    let h = h.rem_euclid(360.0) / 60.0;
+    let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+    let (r, g, b) = match h as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x)
+    };
+    let m = v - c;
+    [((r + m) * 255.0) as u8,
+     ((g + m) * 255.0) as u8,
+     ((b + m) * 255.0) as u8]
+}
+
+/// Parse a string as a complex number.
+///
+/// Accepts the Cartesian form `a + bi` (and variants like `a - bi`, `bi + a`,
+/// a bare real `a`, or a bare imaginary `bi`, with `i` or `j` as the imaginary
+/// unit), falling back to the original `re,im` comma form so existing
+/// invocations keep working.
 fn parse_complex(s: &str) -> Option<Complex<f64>> {
+    if let Some(c) = parse_cartesian(s) {
+        return Some(c);
+    }
     match parse_pair(s, ',') {
         Some((re, im)) => Some(Complex {re, im}),
         None => None
     }
 }
 
+/// Parse a string of the form `a + bi` into a complex number.
+///
+/// The top-level `+`/`-` separating the real and imaginary terms is found by
+/// scanning for a sign that is neither the leading sign nor part of a
+/// floating-point exponent (a sign immediately following `e`/`E`). Each term
+/// is assigned to the real or imaginary component by whether it carries an
+/// `i`/`j` suffix; a missing term defaults to `0.0`.
+fn parse_cartesian(s: &str) -> Option<Complex<f64>> {
+    let bytes = s.as_bytes();
+    let mut split = None;
+    for i in 1..bytes.len() {
+        let b = bytes[i];
+        if b == b'+' || b == b'-' {
+            let prev = bytes[i - 1];
+            if prev == b'e' || prev == b'E' {
+                continue; // sign belongs to an exponent, not a term boundary
+            }
+            split = Some(i);
+            break;
+        }
+    }
+
+    let (first, second) = match split {
+        Some(i) => (&s[..i], &s[i..]), // `second` keeps its leading sign
+        None => (s, "")
+    };
+
+    let mut re = 0.0;
+    let mut im = 0.0;
+    let mut have_re = false;
+    let mut have_im = false;
+    for term in [first, second] {
+        let term: String = term.chars().filter(|c| !c.is_whitespace()).collect();
+        if term.is_empty() {
+            continue;
+        }
+        match parse_imaginary(&term) {
+            Some(value) => {
+                if have_im { return None; }
+                im = value;
+                have_im = true;
+            }
+            None => {
+                let value = f64::from_str(&term).ok()?;
+                if have_re { return None; }
+                re = value;
+                have_re = true;
+            }
+        }
+    }
+
+    if !have_re && !have_im {
+        return None;
+    }
+    Some(Complex { re, im })
+}
+
+/// Parse an imaginary term like `0.35i`, `-i`, or `2j`, returning its
+/// coefficient, or `None` if the term carries no `i`/`j` suffix.
+fn parse_imaginary(s: &str) -> Option<f64> {
+    match s.chars().last() {
+        Some('i') | Some('j') => {}
+        _ => return None
+    }
+    match &s[..s.len() - 1] {
+        "" | "+" => Some(1.0),
+        "-" => Some(-1.0),
+        coeff => f64::from_str(coeff).ok()
+    }
+}
+
 #[test]
 fn test_parse_complex() {
     assert_eq!(parse_complex("-123.23,420.230"), Some(Complex{re:-123.23, im:420.230}));
     assert_eq!(parse_complex(",420.230"), None);
+    assert_eq!(parse_complex("-1.2 + 0.35i"), Some(Complex{re:-1.2, im:0.35}));
+    assert_eq!(parse_complex("-1.2 - 0.35i"), Some(Complex{re:-1.2, im:-0.35}));
+    assert_eq!(parse_complex("0.35i - 1.2"), Some(Complex{re:-1.2, im:0.35}));
+    assert_eq!(parse_complex("-1.2"), Some(Complex{re:-1.2, im:0.0}));
+    assert_eq!(parse_complex("0.35j"), Some(Complex{re:0.0, im:0.35}));
+    assert_eq!(parse_complex("-i"), Some(Complex{re:0.0, im:-1.0}));
+    assert_eq!(parse_complex("1.5e-3 + 2i"), Some(Complex{re:0.0015, im:2.0}));
 }
 
 
 /// ### Returns
 /// `Some<(x,y)>` on success, otherwise `None`
-/// 
+///
 /// ### Overview
 /// Parse the string `s` as a coordinate pair, like `"400x600"` or `"1.0,0.5"`.
-/// 
+///
 /// Specifically, `s` should have the form <left><sep><right>, where <sep> is
 /// the character given by the `separator` argument, and <left> and <right> are
 /// both strings that can be parsed by `T::from_str`.
@@ -81,6 +287,344 @@ fn test_parse_pair() {
     assert_eq!(parse_pair::<f64>("0.5x1.5", 'x'), Some((0.5, 1.5)));
 }
 
+/// ### Overview
+/// Given the row and column of a pixel in the output image, return the
+/// corresponding point on the complex plane.
+///
+/// `bounds` is a pair giving the width and height of the image in pixels.
+/// `pixel` is a (column, row) pair indicating a particular pixel in that image.
+/// The `upper_left` and `lower_right` parameters are points on the complex
+/// plane designating the area our image covers.
+fn pixel_to_point(bounds: (usize, usize),
+                  pixel: (usize, usize),
+                  upper_left: Complex<f64>,
+                  lower_right: Complex<f64>)
+    -> Complex<f64>
+{
+    let (width, height) = (lower_right.re - upper_left.re,
+                           upper_left.im - lower_right.im);
+    Complex {
+        re: upper_left.re + pixel.0 as f64 * width  / bounds.0 as f64,
+        // Why subtraction here? pixel.1 increases as we go down,
+        // but the imaginary component increases as we go up.
+        im: upper_left.im - pixel.1 as f64 * height / bounds.1 as f64
+    }
+}
+
+#[test]
+fn test_pixel_to_point() {
+    assert_eq!(pixel_to_point((100, 200), (25, 175),
+                              Complex { re: -1.0, im:  1.0 },
+                              Complex { re:  1.0, im: -1.0 }),
+               Complex { re: -0.5, im: -0.75 });
+}
+
+/// ### Overview
+/// Render a rectangle of the `kind` fractal into a buffer of pixels.
+///
+/// The `bounds` argument gives the width and height of the region `pixels`
+/// covers. With `palette` as `None` the buffer holds one grayscale byte per
+/// pixel; with `Some(palette)` it holds three bytes per pixel (RGB) and the
+/// continuous escape value is fed through the chosen color ramp. The
+/// `upper_left` and `lower_right` arguments specify points on the complex
+/// plane corresponding to the upper-left and lower-right corners.
+fn render(pixels: &mut [u8],
+          bounds: (usize, usize),
+          upper_left: Complex<f64>,
+          lower_right: Complex<f64>,
+          kind: FractalKind,
+          palette: Option<Palette>)
+{
+    let channels = match palette { None => 1, Some(_) => 3 };
+    assert!(pixels.len() == bounds.0 * bounds.1 * channels);
+
+    for row in 0..bounds.1 {
+        for column in 0..bounds.0 {
+            let point = pixel_to_point(bounds, (column, row),
+                                       upper_left, lower_right);
+            let offset = (row * bounds.0 + column) * channels;
+            match palette {
+                None => {
+                    pixels[offset] =
+                        match escape_time(point, kind, 255) {
+                            None => 0,
+                            Some(count) => 255 - count as u8
+                        };
+                }
+                Some(palette) => {
+                    let rgb = match escape_time_smooth(point, kind, 255) {
+                        None => [0, 0, 0],
+                        Some(value) => palette.color(value / 255.0)
+                    };
+                    pixels[offset..offset + 3].copy_from_slice(&rgb);
+                }
+            }
+        }
+    }
+}
+
+/// ### Overview
+/// The inverse of `pixel_to_point`: given a point on the complex plane, return
+/// the `(column, row)` pixel it falls in, or `None` if the point lies outside
+/// the region covered by `bounds`.
+fn point_to_pixel(bounds: (usize, usize),
+                  point: Complex<f64>,
+                  upper_left: Complex<f64>,
+                  lower_right: Complex<f64>)
+    -> Option<(usize, usize)>
+{
+    let (width, height) = (lower_right.re - upper_left.re,
+                           upper_left.im - lower_right.im);
+    let column = (point.re - upper_left.re) / width * bounds.0 as f64;
+    let row = (upper_left.im - point.im) / height * bounds.1 as f64;
+    if column < 0.0 || row < 0.0 {
+        return None;
+    }
+    let (column, row) = (column as usize, row as usize);
+    if column < bounds.0 && row < bounds.1 {
+        Some((column, row))
+    } else {
+        None
+    }
+}
+
+/// ### Overview
+/// Accumulate the Buddhabrot density of escaping orbits into `counts`.
+///
+/// Draw `samples` random points `c` from the region covered by `bounds` and
+/// iterate `z = z*z + c` up to `limit` steps. Whenever an orbit escapes before
+/// the limit we replay it, incrementing the hit-counter for every pixel a
+/// visited `z` maps back to. Orbits that never escape contribute nothing.
+fn render_buddhabrot(counts: &mut [u32],
+                     bounds: (usize, usize),
+                     upper_left: Complex<f64>,
+                     lower_right: Complex<f64>,
+                     samples: usize,
+                     limit: usize)
+{
+    assert!(counts.len() == bounds.0 * bounds.1);
+
+    let mut rng = rand::thread_rng();
+    let mut orbit: Vec<Complex<f64>> = Vec::with_capacity(limit);
+
+    for _ in 0..samples {
+        let c = Complex {
+            re: rng.gen_range(upper_left.re, lower_right.re),
+            im: rng.gen_range(lower_right.im, upper_left.im)
+        };
+
+        orbit.clear();
+        let mut z = Complex { re: 0.0, im: 0.0 };
+        let mut escaped = false;
+        for _ in 0..limit {
+            z = z * z + c;
+            orbit.push(z);
+            if z.norm_sqr() > 4.0 {
+                escaped = true;
+                break;
+            }
+        }
+
+        if escaped {
+            for &z in &orbit {
+                if let Some((column, row)) =
+                    point_to_pixel(bounds, z, upper_left, lower_right)
+                {
+                    counts[row * bounds.0 + column] += 1;
+                }
+            }
+        }
+    }
+}
+
+/// Normalize a buffer of hit-counts to 8-bit intensities, scaling the densest
+/// cell to `255` and everything else proportionally.
+fn normalize_counts(counts: &[u32]) -> Vec<u8> {
+    let max = counts.iter().copied().max().unwrap_or(0);
+    if max == 0 {
+        return vec![0; counts.len()];
+    }
+    counts.iter()
+        .map(|&count| (count as f64 / max as f64 * 255.0) as u8)
+        .collect()
+}
+
+/// ### Overview
+/// Render a Buddhabrot (or, with `nebula`, a three-channel "nebula" variant)
+/// and write it to `filename`.
+///
+/// The plain variant accumulates a single density buffer and writes it as a
+/// grayscale PNG. The nebula variant runs three passes with different
+/// iteration limits and maps their normalized densities onto the red, green,
+/// and blue channels of an RGB PNG.
+fn render_buddhabrot_image(filename: &str,
+                           bounds: (usize, usize),
+                           upper_left: Complex<f64>,
+                           lower_right: Complex<f64>,
+                           samples: usize,
+                           nebula: bool)
+{
+    if nebula {
+        // One iteration limit per color channel, longest-lived first.
+        let limits = [5000, 500, 50];
+        let mut channels = Vec::with_capacity(3);
+        for &limit in &limits {
+            let mut counts = vec![0u32; bounds.0 * bounds.1];
+            render_buddhabrot(&mut counts, bounds, upper_left, lower_right,
+                              samples, limit);
+            channels.push(normalize_counts(&counts));
+        }
+
+        let mut pixels = vec![0u8; bounds.0 * bounds.1 * 3];
+        for (i, pixel) in pixels.chunks_mut(3).enumerate() {
+            pixel[0] = channels[0][i];
+            pixel[1] = channels[1][i];
+            pixel[2] = channels[2][i];
+        }
+
+        write_image(filename, &pixels, bounds, ColorType::RGB(8))
+            .expect("error writing PNG file");
+    } else {
+        let mut counts = vec![0u32; bounds.0 * bounds.1];
+        render_buddhabrot(&mut counts, bounds, upper_left, lower_right,
+                          samples, 1000);
+        let pixels = normalize_counts(&counts);
+
+        write_image(filename, &pixels, bounds, ColorType::Gray(8))
+            .expect("error writing PNG file");
+    }
+}
+
+/// ### Overview
+/// Write the buffer `pixels`, whose dimensions are given by `bounds`, to the
+/// file named `filename`, encoded as a PNG with the given `color_type`.
+fn write_image(filename: &str, pixels: &[u8], bounds: (usize, usize),
+               color_type: ColorType)
+    -> Result<(), std::io::Error>
+{
+    let output = File::create(filename)?;
+
+    let encoder = PNGEncoder::new(output);
+    encoder.encode(pixels,
+                   bounds.0 as u32, bounds.1 as u32,
+                   color_type)?;
+
+    Ok(())
+}
+
 fn main() {
+    let raw: Vec<String> = std::env::args().collect();
 
-}
\ No newline at end of file
+    // Pull the `--fractal KIND`, `--smooth`, `--palette NAME`, `--buddhabrot`,
+    // `--nebula`, and `--samples N` flags out of the argument list, leaving the
+    // program name and the positional FILE PIXELS UPPERLEFT LOWERRIGHT
+    // arguments in `args`.
+    let mut args: Vec<String> = Vec::new();
+    let mut kind = FractalKind::Mandelbrot;
+    let mut smooth = false;
+    let mut palette = Palette::Hsv;
+    let mut buddhabrot = false;
+    let mut nebula = false;
+    let mut samples: usize = 1_000_000;
+    let mut i = 0;
+    while i < raw.len() {
+        match raw[i].as_str() {
+            "--fractal" => {
+                kind = raw.get(i + 1)
+                    .and_then(|s| s.parse().ok())
+                    .expect("error parsing fractal kind");
+                i += 2;
+            }
+            "--smooth" => {
+                smooth = true;
+                i += 1;
+            }
+            "--palette" => {
+                palette = raw.get(i + 1)
+                    .and_then(|s| s.parse().ok())
+                    .expect("error parsing palette");
+                i += 2;
+            }
+            "--buddhabrot" => {
+                buddhabrot = true;
+                i += 1;
+            }
+            "--nebula" => {
+                buddhabrot = true;
+                nebula = true;
+                i += 1;
+            }
+            "--samples" => {
+                samples = raw.get(i + 1)
+                    .and_then(|s| s.parse().ok())
+                    .expect("error parsing samples");
+                i += 2;
+            }
+            _ => {
+                args.push(raw[i].clone());
+                i += 1;
+            }
+        }
+    }
+
+    if args.len() != 5 {
+        eprintln!("Usage: {} FILE PIXELS UPPERLEFT LOWERRIGHT \
+                   [--fractal KIND] [--smooth] [--palette NAME] \
+                   [--buddhabrot | --nebula] [--samples N]", args[0]);
+        eprintln!("Example: {} mandel.png 1000x750 -1.20,0.35 -1,0.20", args[0]);
+        std::process::exit(1);
+    }
+
+    let bounds = parse_pair(&args[2], 'x')
+        .expect("error parsing image dimensions");
+    let upper_left = parse_complex(&args[3])
+        .expect("error parsing upper left corner point");
+    let lower_right = parse_complex(&args[4])
+        .expect("error parsing lower right corner point");
+
+    if buddhabrot {
+        render_buddhabrot_image(&args[1], bounds, upper_left, lower_right,
+                                samples, nebula);
+        return;
+    }
+
+    // Smooth coloring produces an RGB image; the default is grayscale.
+    let palette = if smooth { Some(palette) } else { None };
+    let channels = match palette { None => 1, Some(_) => 3 };
+    let color_type = match palette { None => ColorType::Gray(8), Some(_) => ColorType::RGB(8) };
+
+    let mut pixels = vec![0; bounds.0 * bounds.1 * channels];
+
+    // Scale the work across as many threads as the machine offers, falling
+    // back to a single thread if the hint is unavailable.
+    let threads = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1);
+    let rows_per_band = bounds.1 / threads + 1;
+
+    {
+        let bands: Vec<&mut [u8]> =
+            pixels.chunks_mut(rows_per_band * bounds.0 * channels).collect();
+
+        crossbeam::scope(|spawner| {
+            for (i, band) in bands.into_iter().enumerate() {
+                let top = rows_per_band * i;
+                let height = band.len() / (bounds.0 * channels);
+                let band_bounds = (bounds.0, height);
+                let band_upper_left =
+                    pixel_to_point(bounds, (0, top), upper_left, lower_right);
+                let band_lower_right =
+                    pixel_to_point(bounds, (bounds.0, top + height),
+                                   upper_left, lower_right);
+
+                spawner.spawn(move |_| {
+                    render(band, band_bounds, band_upper_left, band_lower_right,
+                           kind, palette);
+                });
+            }
+        }).unwrap();
+    }
+
+    write_image(&args[1], &pixels, bounds, color_type)
+        .expect("error writing PNG file");
+}